@@ -0,0 +1,87 @@
+//! Transparent compression support for unitig input/output streams.
+//!
+//! Input files are sniffed by magic bytes rather than by extension, so a
+//! gzipped FASTA file named `unitigs.fasta` (not `.fasta.gz`) is still
+//! decompressed correctly. Output compression is selected explicitly by the
+//! caller since there is no sensible way to infer it from the data.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression codec to use for an output stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompressionFormat {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl std::str::FromStr for CompressionFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(CompressionFormat::None),
+            "gzip" => Ok(CompressionFormat::Gzip),
+            "zstd" => Ok(CompressionFormat::Zstd),
+            other => Err(format!(
+                "Unknown compression format '{}' (expected gzip, zstd or none)",
+                other
+            )),
+        }
+    }
+}
+
+/// Opens `path` for reading, transparently wrapping it in a streaming
+/// decoder if its first bytes match a known compression magic number.
+/// Pass "-" to read from stdin (never treated as compressed, since we
+/// can't rewind it to sniff without buffering the whole stream).
+pub fn open_input(path: &str) -> io::Result<Box<dyn Read>> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin()));
+    }
+
+    let mut sniffer = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let n = sniffer.read(&mut magic)?;
+    drop(sniffer);
+
+    let file = File::open(path)?;
+    if n >= GZIP_MAGIC.len() && magic[..2] == GZIP_MAGIC {
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else if n >= ZSTD_MAGIC.len() && magic == ZSTD_MAGIC {
+        Ok(Box::new(zstd::stream::read::Decoder::new(file)?))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Opens `path` for writing, wrapping it in an encoder for `format` at the
+/// given compression level. Pass "-" to stream to stdout.
+pub fn open_output(
+    path: &str,
+    format: CompressionFormat,
+    level: u32,
+) -> io::Result<Box<dyn Write>> {
+    let raw: Box<dyn Write> = if path == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(path)?)
+    };
+
+    match format {
+        CompressionFormat::None => Ok(raw),
+        CompressionFormat::Gzip => Ok(Box::new(GzEncoder::new(raw, Compression::new(level)))),
+        CompressionFormat::Zstd => {
+            let encoder = zstd::stream::write::Encoder::new(raw, level as i32)?;
+            Ok(Box::new(encoder.auto_finish()))
+        }
+    }
+}
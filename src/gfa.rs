@@ -0,0 +1,70 @@
+//! GFA1 export of the de Bruijn graph built in [`crate::build_dbg`].
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::{rc, Orientation, DBG};
+
+fn flip_strand(c: char) -> char {
+    match c {
+        '+' => '-',
+        '-' => '+',
+        _ => unreachable!("strand char is always '+' or '-'"),
+    }
+}
+
+/// Writes `dbg` as GFA1: one `S` line per unitig (sequence in its final
+/// chosen `orientation`), and one `L` line per overlap edge with a
+/// `(k-1)M` CIGAR. Each overlap is symmetric in `dbg.edges` (it appears
+/// once from each endpoint's perspective), so the mirror of every emitted
+/// edge is tracked and skipped.
+pub fn write_gfa<W: Write>(mut out: W, dbg: &DBG, orientations: &[Orientation], k: usize) -> io::Result<()> {
+    for i in 0..dbg.unitigs.sequence_count() {
+        let unitig = dbg.unitigs.get(i).unwrap();
+        let seq: Vec<u8> = match orientations[i] {
+            Orientation::Forward => unitig.seq.to_vec(),
+            Orientation::Reverse => unitig.seq.iter().rev().map(|&c| rc(c)).collect(),
+        };
+        writeln!(
+            out,
+            "S\t{}\t{}",
+            String::from_utf8_lossy(unitig.head),
+            String::from_utf8_lossy(&seq)
+        )?;
+    }
+
+    // Seen mirrors of already-emitted edges, keyed by (from, to, from_strand, to_strand).
+    let mut emitted_mirrors: HashSet<(usize, usize, char, char)> = HashSet::new();
+    for edges_from_i in dbg.edges.iter() {
+        for edge in edges_from_i.iter() {
+            // edge.from_orientation/to_orientation are relative to how build_dbg
+            // originally read the unitigs, not to the final orientations[] the
+            // S lines are written in. Re-derive the strand as seen in the
+            // emitted sequence: '+' if the unitig ended up in the orientation
+            // this edge was recorded under, '-' if it got flipped since.
+            let from_strand = if edge.from_orientation == orientations[edge.from] { '+' } else { '-' };
+            let to_strand = if edge.to_orientation == orientations[edge.to] { '+' } else { '-' };
+
+            let key = (edge.from, edge.to, from_strand, to_strand);
+            if emitted_mirrors.contains(&key) {
+                continue;
+            }
+            let mirror = (edge.to, edge.from, flip_strand(to_strand), flip_strand(from_strand));
+            emitted_mirrors.insert(mirror);
+
+            let from_header = dbg.unitigs.get(edge.from).unwrap().head;
+            let to_header = dbg.unitigs.get(edge.to).unwrap().head;
+            writeln!(
+                out,
+                "L\t{}\t{}\t{}\t{}\t{}M",
+                String::from_utf8_lossy(from_header),
+                from_strand,
+                String::from_utf8_lossy(to_header),
+                to_strand,
+                k - 1,
+            )?;
+        }
+    }
+
+    Ok(())
+}
@@ -4,9 +4,66 @@ use jseqio::record::*;
 use jseqio::writer;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::process::ExitCode;
+
+use clap::Parser;
+use rayon::prelude::*;
+
+mod compression;
+use compression::CompressionFormat;
+
+mod gfa;
+mod report;
+
+/// Orients unitigs consistently by walking the de Bruijn graph they induce.
+#[derive(Parser, Debug)]
+#[command(name = "unitig_flipper", about = "Picks a consistent strand for each unitig in a unitig set")]
+struct Cli {
+    /// Length of the k-mers used to build the unitigs (must be at least 2)
+    #[arg(short = 'k', long = "kmer-length")]
+    k: usize,
+
+    /// Input FASTA/FASTQ file, optionally gzip- or zstd-compressed. Use "-" for stdin.
+    #[arg(short = 'i', long = "input", default_value = "-")]
+    input: String,
+
+    /// Output file. Use "-" for stdout.
+    #[arg(short = 'o', long = "output", default_value = "-")]
+    output: String,
+
+    /// Number of threads to use (defaults to the rayon global default)
+    #[arg(long = "threads")]
+    threads: Option<usize>,
+
+    /// Within each component, flip the whole component instead of the root
+    /// when that yields fewer reverse-complemented unitigs overall
+    #[arg(long = "minimize-flips", default_value_t = false)]
+    minimize_flips: bool,
+
+    /// Abort with a nonzero exit code if any component's overlaps can't be
+    /// consistently oriented (i.e. the constraint graph isn't 2-colorable)
+    #[arg(long = "strict", default_value_t = false)]
+    strict: bool,
+
+    /// Write the de Bruijn graph (in the chosen output orientation) as GFA1 to this path
+    #[arg(long = "gfa")]
+    gfa: Option<String>,
+
+    /// Write a TSV of (header, original index, final orientation, component id) to this path
+    #[arg(long = "report")]
+    report: Option<String>,
+
+    /// Output compression format
+    #[arg(long = "compress", default_value = "none")]
+    compress: CompressionFormat,
+
+    /// Output compression level (codec-specific; ignored when --compress is none)
+    #[arg(long = "compression-level", default_value_t = 6)]
+    compression_level: u32,
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
-enum Orientation{
+pub(crate) enum Orientation{
     Forward,
     Reverse,
 }
@@ -27,16 +84,16 @@ enum Position{
 }
 
 #[derive(Copy, Clone, Debug)]
-struct Edge{
-    from: usize,
-    to: usize,
-    from_orientation: Orientation,
-    to_orientation: Orientation,
+pub(crate) struct Edge{
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) from_orientation: Orientation,
+    pub(crate) to_orientation: Orientation,
 }
 
-struct DBG{
-    unitigs: SeqDB, // A sequence database with random access to the i-th unitig
-    edges: Vec<Vec<Edge>> // edges[i] = outgoing edges from unitig i
+pub(crate) struct DBG{
+    pub(crate) unitigs: SeqDB, // A sequence database with random access to the i-th unitig
+    pub(crate) edges: Vec<Vec<Edge>> // edges[i] = outgoing edges from unitig i
 }
 
 struct MapValue{
@@ -44,13 +101,44 @@ struct MapValue{
     position: Position,
 }
 
-fn insert_if_not_present(map: &mut HashMap<Vec<u8>, Vec<MapValue>>, key: &[u8]){
-    if !map.contains_key(key){
-        map.insert(key.to_owned(), Vec::<MapValue>::new());
+// A (k-1)-mer used as a border map key. For the common case k-1 <= 32 it's
+// packed 2 bits/base into a u64, which avoids a heap allocation and hashes
+// an integer instead of raw bytes. Longer borders (or ones containing a
+// non-ACGT character) fall back to an owned byte vector.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum BorderKey{
+    Packed(u64),
+    Owned(Vec<u8>),
+}
+
+fn encode_base_2bit(c: u8) -> Option<u64>{
+    match c{
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
     }
 }
 
-fn rc(c: u8) -> u8{
+fn make_border_key(kmer: &[u8]) -> BorderKey{
+    if kmer.len() <= 32{
+        let mut code: u64 = 0;
+        let mut packable = true;
+        for &c in kmer{
+            match encode_base_2bit(c){
+                Some(bits) => code = (code << 2) | bits,
+                None => { packable = false; break; }
+            }
+        }
+        if packable{
+            return BorderKey::Packed(code);
+        }
+    }
+    BorderKey::Owned(kmer.to_owned())
+}
+
+pub(crate) fn rc(c: u8) -> u8{
     match c{
         b'A' => b'T',
         b'T' => b'A',
@@ -60,8 +148,8 @@ fn rc(c: u8) -> u8{
     }
 }
 
-fn push_edges(from: usize, from_orientation: Orientation, to_orientation: Orientation, to_position: Position, linking_kmer: &[u8], edges: &mut Vec<Vec<Edge>>, borders: &HashMap<Vec<u8>, Vec<MapValue>>){
-    if let Some(vec) = borders.get(linking_kmer){
+fn push_edges(from: usize, from_orientation: Orientation, to_orientation: Orientation, to_position: Position, linking_kmer: &[u8], edges: &mut Vec<Vec<Edge>>, borders: &HashMap<BorderKey, Vec<MapValue>>){
+    if let Some(vec) = borders.get(&make_border_key(linking_kmer)){
         for x in vec.iter(){
             if x.position == to_position {
                 let edge = Edge{from, to: x.unitig_id, from_orientation, to_orientation};
@@ -72,35 +160,50 @@ fn push_edges(from: usize, from_orientation: Orientation, to_orientation: Orient
 }
 
 
-fn build_dbg(unitigs: SeqDB, k: usize) -> DBG{
-    let mut borders: HashMap<Vec<u8>, Vec<MapValue>> = HashMap::new(); // (k-1)-mer to locations of that k-mer
-
-    let n = unitigs.sequence_count();
-
-    // Build borders map
-    for i in 0..n{
+// Every unitig must be at least k-1 long, since build_dbg slices out its
+// first and last (k-1)-mers. Checking this up front turns an out-of-bounds
+// panic deep in build_dbg into a clear error message.
+fn validate_unitig_lengths(unitigs: &SeqDB, k: usize) -> Result<(), String> {
+    for i in 0..unitigs.sequence_count() {
         let unitig = unitigs.get(i).unwrap();
+        if unitig.seq.len() < k - 1 {
+            return Err(format!(
+                "Unitig {} ('{}') has length {}, which is shorter than k-1 = {}",
+                i,
+                String::from_utf8_lossy(unitig.head),
+                unitig.seq.len(),
+                k - 1
+            ));
+        }
+    }
+    Ok(())
+}
 
-        let first = &unitig.seq[..k-1];
-        let last = &unitig.seq[unitig.seq.len()-(k-1)..];
-
-        insert_if_not_present(&mut borders, first);
-        insert_if_not_present(&mut borders, last);
-
-        borders.get_mut(first).unwrap().push(
-            MapValue{
-                unitig_id: i, 
-                position: Position::Start, 
-            }
-        );
+fn build_dbg(unitigs: SeqDB, k: usize) -> DBG{
+    let n = unitigs.sequence_count();
 
-        borders.get_mut(last).unwrap().push(
-            MapValue{
-                unitig_id: i, 
-                position: Position::End, 
+    // Build the borders map (packed (k-1)-mer -> locations of that border) in
+    // parallel: each unitig contributes two (key, MapValue) entries, folded
+    // into a per-thread map and then reduced into one.
+    let borders: HashMap<BorderKey, Vec<MapValue>> = (0..n)
+        .into_par_iter()
+        .fold(HashMap::new, |mut acc: HashMap<BorderKey, Vec<MapValue>>, i| {
+            let unitig = unitigs.get(i).unwrap();
+
+            let first = make_border_key(&unitig.seq[..k-1]);
+            let last = make_border_key(&unitig.seq[unitig.seq.len()-(k-1)..]);
+
+            acc.entry(first).or_insert_with(Vec::new).push(MapValue{unitig_id: i, position: Position::Start});
+            acc.entry(last).or_insert_with(Vec::new).push(MapValue{unitig_id: i, position: Position::End});
+
+            acc
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (key, mut values) in b{
+                a.entry(key).or_insert_with(Vec::new).append(&mut values);
             }
-        );
-    }
+            a
+        });
 
     let mut edges = Vec::<Vec::<Edge>>::new();
     edges.resize_with(n, || Vec::<Edge>::new()); // Allocate n edge lists
@@ -131,34 +234,80 @@ fn build_dbg(unitigs: SeqDB, k: usize) -> DBG{
     DBG {unitigs, edges}
 }
 
-fn pick_orientations(dbg: &DBG) -> Vec<Orientation>{
+/// Per-component summary of how many orientation constraints (edges) agreed
+/// with the orientation the DFS assigned, versus how many contradicted it.
+/// The edge along which a unitig is first reached sets its orientation and
+/// counts as satisfied. A nonzero `edges_violated` means the component's
+/// constraint graph is not 2-colorable: there is no way to orient every
+/// unitig consistently with every overlap.
+struct ComponentReport{
+    component_id: usize,
+    size: usize,
+    edges_satisfied: usize,
+    edges_violated: usize,
+}
+
+fn pick_orientations(dbg: &DBG, minimize_flips: bool) -> (Vec<Orientation>, Vec<usize>, Vec<ComponentReport>){
     let mut orientations = Vec::<Orientation>::new();
     orientations.resize(dbg.unitigs.sequence_count(), Orientation::Forward);
 
+    let mut component_ids = vec![0usize; dbg.unitigs.sequence_count()];
+
     let mut visited = vec![false; dbg.unitigs.sequence_count()];
 
     let mut stack = Vec::<(usize, Orientation)>::new(); // Reused DFS stack between iterations
-    let mut n_components: usize = 0;    
+    let mut component_members = Vec::<usize>::new(); // Reused between iterations
+    let mut n_components: usize = 0;
+    let mut reports = Vec::<ComponentReport>::new();
     for component_root in 0..dbg.unitigs.sequence_count(){
         if visited[component_root]{
             continue;
         }
 
         n_components += 1;
-        // Arbitrarily orient the root as forward        
-        stack.push((component_root, Orientation::Forward));
+        component_members.clear();
+
+        // Seed the root directly instead of pushing it onto the stack, so
+        // that every pop inside the loop below corresponds to an edge
+        // constraint rather than the arbitrary root choice.
+        visited[component_root] = true;
+        orientations[component_root] = Orientation::Forward;
+        component_members.push(component_root);
+
+        let mut component_size: usize = 1;
+        let mut edges_satisfied: usize = 0;
+        let mut edges_violated: usize = 0;
+
+        for edge in dbg.edges[component_root].iter(){
+            let next_orientation = match (edge.from_orientation, edge.to_orientation){
+                (Orientation::Forward, Orientation::Forward) => Orientation::Forward,
+                (Orientation::Forward, Orientation::Reverse) => Orientation::Reverse,
+                (Orientation::Reverse, Orientation::Forward) => Orientation::Reverse,
+                (Orientation::Reverse, Orientation::Reverse) => Orientation::Forward,
+            };
+            stack.push((edge.to, next_orientation));
+        }
 
-        let mut component_size: usize = 0;
-        // DFS from root and orient all reachable unitigs the same way
+        // DFS from root: each popped entry is the orientation implied by one
+        // edge. If the target is unvisited, that orientation becomes final.
+        // If it's already visited, check it against the stored orientation
+        // to detect a conflicting (non-2-colorable) constraint.
         while let Some((unitig_id, orientation)) = stack.pop(){
             if visited[unitig_id]{
+                if orientations[unitig_id] == orientation {
+                    edges_satisfied += 1;
+                } else {
+                    edges_violated += 1;
+                }
                 continue;
             }
 
             component_size += 1;
             visited[unitig_id] = true;
             orientations[unitig_id] = orientation;
-    
+            component_members.push(unitig_id);
+            edges_satisfied += 1;
+
             for edge in dbg.edges[unitig_id].iter(){
                 let next_orientation = match (edge.from_orientation, edge.to_orientation){
                     (Orientation::Forward, Orientation::Forward) => orientation,
@@ -169,25 +318,97 @@ fn pick_orientations(dbg: &DBG) -> Vec<Orientation>{
                 stack.push((edge.to, next_orientation));
             }
         }
-        eprintln!("Component size = {}", component_size);
+
+        // The DFS only fixes orientations *relative* to the root: the whole
+        // component can be left as-is (root forward) or flipped entirely
+        // (root reverse). Pick whichever yields fewer reverse-complemented
+        // unitigs when --minimize-flips is set; otherwise always keep the
+        // root forward, matching the previous behavior.
+        let flips_if_root_forward = component_members.iter()
+            .filter(|&&id| orientations[id] == Orientation::Reverse)
+            .count();
+        let flips_if_root_reverse = component_size - flips_if_root_forward;
+
+        let chosen_flips = if minimize_flips && flips_if_root_reverse < flips_if_root_forward {
+            for &id in component_members.iter(){
+                orientations[id] = orientations[id].flip();
+            }
+            flips_if_root_reverse
+        } else {
+            flips_if_root_forward
+        };
+
+        eprintln!(
+            "Component {}: size = {}, flipped {} unitig{}, edges satisfied = {}, edges violated = {}",
+            n_components, component_size, chosen_flips, match chosen_flips == 1 {true => "", false => "s"},
+            edges_satisfied, edges_violated,
+        );
+
+        for &id in component_members.iter(){
+            component_ids[id] = n_components - 1;
+        }
+
+        reports.push(ComponentReport{
+            component_id: n_components - 1,
+            size: component_size,
+            edges_satisfied,
+            edges_violated,
+        });
     }
 
     eprintln!("Found {} component{}", n_components, match n_components > 1 {true => "s", false => ""});
 
-    orientations
+    (orientations, component_ids, reports)
 }
 
-fn main() {
-    let filename = std::env::args().nth(1).unwrap();
-    let k = std::env::args().nth(2).unwrap().parse::<usize>().unwrap();
-    let reader = DynamicFastXReader::from_file(&filename).unwrap();
+fn run(cli: Cli) -> Result<(), String> {
+    if cli.k < 2 {
+        return Err(format!("k must be at least 2, got {}", cli.k));
+    }
+
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| format!("Failed to set up thread pool: {}", e))?;
+    }
+
+    let input = compression::open_input(&cli.input).map_err(|e| format!("Failed to open input '{}': {}", cli.input, e))?;
+    let mut reader = DynamicFastXReader::from_input_stream(Box::new(input), &cli.input)
+        .map_err(|e| format!("Failed to parse input '{}': {}", cli.input, e))?;
     let filetype = reader.filetype();
-    let db = reader.into_db().unwrap();
-    let dbg = build_dbg(db, k);
-    let orientations = pick_orientations(&dbg);
+    let db = reader.into_db().map_err(|e| format!("Failed to load sequences: {}", e))?;
+
+    validate_unitig_lengths(&db, cli.k)?;
+
+    let dbg = build_dbg(db, cli.k);
+    let (orientations, component_ids, component_reports) = pick_orientations(&dbg, cli.minimize_flips);
+
+    let total_violated: usize = component_reports.iter().map(|r| r.edges_violated).sum();
+    if cli.strict && total_violated > 0 {
+        let conflicting_components = component_reports.iter().filter(|r| r.edges_violated > 0).count();
+        return Err(format!(
+            "{} conflicting edge{} across {} component{}: unitig set is not consistently orientable",
+            total_violated, match total_violated == 1 {true => "", false => "s"},
+            conflicting_components, match conflicting_components == 1 {true => "", false => "s"},
+        ));
+    }
+
+    if let Some(gfa_path) = &cli.gfa {
+        let file = std::fs::File::create(gfa_path).map_err(|e| format!("Failed to open GFA output '{}': {}", gfa_path, e))?;
+        gfa::write_gfa(std::io::BufWriter::new(file), &dbg, &orientations, cli.k)
+            .map_err(|e| format!("Failed to write GFA to '{}': {}", gfa_path, e))?;
+    }
+
+    if let Some(report_path) = &cli.report {
+        let file = std::fs::File::create(report_path).map_err(|e| format!("Failed to open report '{}': {}", report_path, e))?;
+        report::write_report(std::io::BufWriter::new(file), &dbg, &orientations, &component_ids)
+            .map_err(|e| format!("Failed to write report to '{}': {}", report_path, e))?;
+    }
 
-    // Todo: gzip
-    let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stdout(filetype, false);
+    let output = compression::open_output(&cli.output, cli.compress, cli.compression_level)
+        .map_err(|e| format!("Failed to open output '{}': {}", cli.output, e))?;
+    let mut writer = jseqio::writer::DynamicFastXWriter::new_to_stream(Box::new(output), filetype, false);
     for i in 0..dbg.unitigs.sequence_count(){
         let orientation = orientations[i];
         let rec: OwnedRecord = match orientation{
@@ -201,4 +422,16 @@ fn main() {
         writer.write(&rec);
     }
 
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,29 @@
+//! TSV sidecar recording the flip decision made for each unitig.
+
+use std::io::{self, Write};
+
+use crate::{Orientation, DBG};
+
+/// Writes one row per unitig: its header, its original index, the final
+/// `Orientation` it was assigned, and the id of the connected component
+/// it belongs to (as counted in [`crate::pick_orientations`]). This is a
+/// machine-readable audit of exactly which unitigs got reverse-complemented.
+pub fn write_report<W: Write>(mut out: W, dbg: &DBG, orientations: &[Orientation], component_ids: &[usize]) -> io::Result<()> {
+    writeln!(out, "header\toriginal_index\torientation\tcomponent_id")?;
+    for i in 0..dbg.unitigs.sequence_count() {
+        let unitig = dbg.unitigs.get(i).unwrap();
+        let orientation_str = match orientations[i] {
+            Orientation::Forward => "Forward",
+            Orientation::Reverse => "Reverse",
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            String::from_utf8_lossy(unitig.head),
+            i,
+            orientation_str,
+            component_ids[i],
+        )?;
+    }
+    Ok(())
+}